@@ -0,0 +1,43 @@
+//! Compares coroutine spawn throughput with and without stack recycling.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::sync::Arc;
+use tinycoro::{Coroutine, StackPool};
+
+const STACK_SIZE: usize = 64 * 1024;
+
+fn spawn_unpooled(iters: u64) {
+    for _ in 0..iters {
+        let mut co = Coroutine::spawn(|| {}, STACK_SIZE).expect("spawn failed");
+        co.resume().expect("resume failed");
+    }
+}
+
+fn spawn_pooled(pool: &Arc<StackPool>, iters: u64) {
+    for _ in 0..iters {
+        let mut co = Coroutine::spawn_in(pool, || {}, STACK_SIZE).expect("spawn failed");
+        co.resume().expect("resume failed");
+    }
+}
+
+fn bench_spawn(c: &mut Criterion) {
+    c.bench_function("spawn_unpooled", |b| {
+        b.iter_custom(|iters| {
+            let start = std::time::Instant::now();
+            spawn_unpooled(iters);
+            start.elapsed()
+        });
+    });
+
+    c.bench_function("spawn_pooled", |b| {
+        let pool = Arc::new(StackPool::new(64));
+        b.iter_custom(|iters| {
+            let start = std::time::Instant::now();
+            spawn_pooled(&pool, iters);
+            start.elapsed()
+        });
+    });
+}
+
+criterion_group!(benches, bench_spawn);
+criterion_main!(benches);
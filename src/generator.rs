@@ -0,0 +1,189 @@
+//! A typed generator built on top of [`Coroutine`]'s storage API.
+//!
+//! This gives yield-value semantics (closer to Rust's native `gen` blocks) without
+//! requiring callers to juggle `push`/`pop`/`resume` and raw coroutine storage directly.
+
+use crate::{pop_raw, push_raw, running, Coroutine, CoroutineError, CoroutineState};
+use core::marker::PhantomData;
+
+/// The result of resuming a [`Generator`]: either it yielded a value, or it ran to
+/// completion and produced its final value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeneratorState<Y, D> {
+    /// The generator yielded a value and is still suspended.
+    Yielded(Y),
+    /// The generator ran to completion, producing its final value.
+    Done(D),
+}
+
+/// A coroutine that yields a sequence of `Y` values, receiving an `R` back on each
+/// resume, and finally produces a `D` value when it completes.
+///
+/// Built on [`Coroutine::push`]/[`Coroutine::pop`]: every value crossing the boundary
+/// between driver and body goes through the coroutine's LIFO storage, so yielded types
+/// that don't fit in the default storage size surface as `CoroutineError::NotEnoughSpace`.
+pub struct Generator<Y, R, D = ()> {
+    coroutine: Coroutine,
+    _marker: PhantomData<fn(R) -> (Y, D)>,
+}
+
+impl<Y: 'static, R: 'static, D: 'static> Generator<Y, R, D> {
+    /// Create a generator from a closure that yields via [`yield_value`].
+    ///
+    /// # Errors
+    ///
+    /// Returns `CoroutineError` if the underlying coroutine cannot be created.
+    pub fn new<F>(f: F, stack_size: usize) -> Result<Self, CoroutineError>
+    where
+        F: FnOnce() -> D + 'static,
+    {
+        let coroutine = Coroutine::spawn(
+            move || {
+                if let Some(co) = running() {
+                    // The very first `resume` pushes an `r` before this body has a
+                    // chance to consume it via `yield_value`; discard it here so it
+                    // isn't leaked on the coroutine's storage.
+                    let _: Result<R, CoroutineError> = pop_raw(co);
+                }
+                let done = f();
+                if let Some(co) = running() {
+                    // Storage bytes are simply memcpy'd out; forget `done` so its
+                    // destructor doesn't run twice once the driver pops its copy.
+                    if push_raw(co, &done).is_ok() {
+                        core::mem::forget(done);
+                    }
+                }
+            },
+            stack_size,
+        )?;
+
+        Ok(Generator {
+            coroutine,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Resume the generator, sending it `r`.
+    ///
+    /// Pushes `r` into the coroutine's storage and resumes it. If the generator is
+    /// still suspended afterwards, pops and returns its yielded value; if it finished,
+    /// pops and returns its final value instead.
+    ///
+    /// # Errors
+    ///
+    /// Returns `CoroutineError` if pushing, resuming, or popping fails.
+    pub fn resume(&mut self, r: R) -> Result<GeneratorState<Y, D>, CoroutineError> {
+        self.coroutine.push(&r)?;
+        // `push` memcpy'd `r`'s bytes into the coroutine's storage, so `r` must be
+        // forgotten now to avoid dropping it twice. If `resume` then fails before the
+        // body ever runs (e.g. the generator is already `Dead`), nothing will ever pop
+        // that copy back out to drop it; pop it back out ourselves in that case so it
+        // drops normally instead of leaking.
+        core::mem::forget(r);
+        if let Err(err) = self.coroutine.resume() {
+            let _: Result<R, CoroutineError> = self.coroutine.pop();
+            return Err(err);
+        }
+        if self.coroutine.status() == CoroutineState::Suspended {
+            Ok(GeneratorState::Yielded(self.coroutine.pop()?))
+        } else {
+            Ok(GeneratorState::Done(self.coroutine.pop()?))
+        }
+    }
+
+    /// The current status of the underlying coroutine.
+    #[must_use]
+    pub fn status(&self) -> CoroutineState {
+        self.coroutine.status()
+    }
+}
+
+impl<Y: 'static, D: 'static> Iterator for Generator<Y, (), D> {
+    type Item = Y;
+
+    fn next(&mut self) -> Option<Y> {
+        match self.resume(()) {
+            Ok(GeneratorState::Yielded(y)) => Some(y),
+            Ok(GeneratorState::Done(_)) | Err(_) => None,
+        }
+    }
+}
+
+/// Yield `y` from within a [`Generator`] body, returning the value sent by the next
+/// [`Generator::resume`] call.
+///
+/// # Errors
+///
+/// Returns `CoroutineError::InvalidCoroutine` if called outside of a running coroutine,
+/// or `CoroutineError::NotEnoughSpace` if `Y` doesn't fit in the coroutine's storage.
+pub fn yield_value<Y, R>(y: Y) -> Result<R, CoroutineError> {
+    let co = running().ok_or(CoroutineError::InvalidCoroutine)?;
+    push_raw(co, &y)?;
+    core::mem::forget(y);
+    crate::yield_current()?;
+    pop_raw(co)
+}
+
+// Requires the `std` feature; see the module-level comment on the `lib.rs` test module
+// for why there's no equivalent under plain `no_std`.
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    const STACK_SIZE: usize = 64 * 1024;
+
+    #[test]
+    fn yields_until_done() {
+        let mut gen = Generator::<i32, (), &'static str>::new(
+            || {
+                let _: () = yield_value(1).unwrap();
+                let _: () = yield_value(2).unwrap();
+                "done"
+            },
+            STACK_SIZE,
+        )
+        .unwrap();
+
+        assert_eq!(gen.resume(()).unwrap(), GeneratorState::Yielded(1));
+        assert_eq!(gen.resume(()).unwrap(), GeneratorState::Yielded(2));
+        assert_eq!(gen.resume(()).unwrap(), GeneratorState::Done("done"));
+    }
+
+    #[test]
+    fn iterator_adapter_stops_after_done() {
+        let gen = Generator::<i32, (), ()>::new(
+            || {
+                let _: () = yield_value(1).unwrap();
+                let _: () = yield_value(2).unwrap();
+            },
+            STACK_SIZE,
+        )
+        .unwrap();
+
+        let values: Vec<i32> = gen.collect();
+        assert_eq!(values, [1, 2]);
+    }
+
+    #[test]
+    fn resuming_past_completion_errors_without_leaking_the_pushed_value() {
+        let mut gen = Generator::<(), alloc::rc::Rc<()>, ()>::new(|| {}, STACK_SIZE).unwrap();
+        let sent = alloc::rc::Rc::new(());
+
+        assert_eq!(
+            gen.resume(alloc::rc::Rc::clone(&sent)).unwrap(),
+            GeneratorState::Done(())
+        );
+        // The generator is already `Dead`; the body never gets a chance to pop this
+        // `r`, so `resume` must report an error and drop it instead of leaking it.
+        assert!(gen.resume(alloc::rc::Rc::clone(&sent)).is_err());
+        assert_eq!(alloc::rc::Rc::strong_count(&sent), 1);
+    }
+
+    #[test]
+    fn panicking_body_surfaces_as_panicked_error() {
+        let mut gen = Generator::<(), (), ()>::new(|| panic!("boom"), STACK_SIZE).unwrap();
+        let err = gen.resume(()).unwrap_err();
+        assert_eq!(err, CoroutineError::Panicked);
+    }
+}
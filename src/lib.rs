@@ -2,12 +2,31 @@
 //!
 //! This crate provides safe and unsafe bindings to minicoro, a minimal asymmetric
 //! stackful cross-platform coroutine library in pure C.
+//!
+//! # Panics without the `std` feature
+//!
+//! With the `std` feature enabled, a panic inside a spawned closure is caught at the
+//! FFI boundary and turned into `CoroutineError::Panicked` from the next [`Coroutine::resume`]
+//! call. Without it, there is no `catch_unwind` to reach for: a panic that unwinds out
+//! of a coroutine's body crosses back into minicoro's C trampoline, which is undefined
+//! behavior. Closures spawned in a `no_std` build must not panic.
 
-#![no_std]
+#![cfg_attr(not(feature = "std"), no_std)]
 #![allow(non_upper_case_globals)]
 #![allow(non_camel_case_types)]
 #![allow(non_snake_case)]
 
+extern crate alloc;
+
+mod generator;
+pub use generator::{yield_value, Generator, GeneratorState};
+
+mod pool;
+pub use pool::StackPool;
+
+mod scheduler;
+pub use scheduler::{yield_to_scheduler, Scheduler, TaskId};
+
 // Include the generated bindings in a private module
 mod ffi {
     #![allow(non_upper_case_globals)]
@@ -18,9 +37,34 @@ mod ffi {
     include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
 }
 
+use alloc::boxed::Box;
+use alloc::sync::Arc;
 use core::ptr;
 use thiserror::Error;
 
+/// A type-erased, heap-allocated coroutine body.
+///
+/// Boxing twice (`Box<dyn FnOnce()>` is a fat pointer) lets us hand minicoro a single
+/// thin pointer via `mco_desc.user_data`.
+type BoxedBody = Box<dyn FnOnce() + 'static>;
+
+/// A caught panic payload, as produced by `std::panic::catch_unwind`.
+#[cfg(feature = "std")]
+type PanicPayload = Box<dyn core::any::Any + Send + 'static>;
+
+/// What `mco_desc.user_data` points to for coroutines created via [`Coroutine::spawn`]
+/// or [`Coroutine::spawn_in`].
+///
+/// `body` is taken (leaving `None`) the moment the trampoline starts running it, so
+/// the trampoline can tell a fresh entry from one that's already underway. With the
+/// `std` feature, a panicking body's payload is stashed in `panic` instead of being
+/// allowed to unwind through the `extern "C"` trampoline frame.
+struct Entry {
+    body: Option<BoxedBody>,
+    #[cfg(feature = "std")]
+    panic: Option<PanicPayload>,
+}
+
 // Re-export only what we need for the public API
 #[doc(hidden)]
 pub use ffi::mco_coro;
@@ -28,6 +72,16 @@ pub use ffi::mco_coro;
 /// A safe wrapper around a minicoro coroutine
 pub struct Coroutine {
     inner: *mut mco_coro,
+    /// Keeps a pool-backed coroutine's [`StackPool`] alive for at least as long as
+    /// this `Coroutine`, since `mco_destroy` (and any further `mco_create` calls
+    /// sharing the pool) invoke callbacks that dereference it. `None` for coroutines
+    /// created via [`Coroutine::new`]/[`Coroutine::spawn`].
+    pool: Option<Arc<StackPool>>,
+    /// Whether a panic payload (if any) left behind by [`trampoline`] has already been
+    /// reclaimed by [`Coroutine::resume`]. Only coroutines spawned from an [`Entry`]
+    /// (i.e. via `spawn`/`spawn_in`) ever have one to reclaim.
+    #[cfg(feature = "std")]
+    panic_reaped: bool,
 }
 
 impl Coroutine {
@@ -49,19 +103,91 @@ impl Coroutine {
 
         let result = unsafe { ffi::mco_create(&raw mut co, (&raw const desc).cast_mut()) };
         if result == ffi::mco_result_MCO_SUCCESS {
-            Ok(Coroutine { inner: co })
+            Ok(Coroutine {
+                inner: co,
+                pool: None,
+                #[cfg(feature = "std")]
+                panic_reaped: false,
+            })
         } else {
             Err(CoroutineError::from_raw(result))
         }
     }
 
+    /// Spawn a coroutine from a Rust closure
+    ///
+    /// The closure is boxed and handed to minicoro as `mco_desc.user_data`; a private
+    /// trampoline retrieves it via `mco_get_user_data` and runs it on the coroutine's
+    /// own stack the first time it is resumed.
+    ///
+    /// # Lifetime invariant
+    ///
+    /// If a `Coroutine` is dropped before it is ever resumed, or after it finishes
+    /// running, the closure is cleanly reclaimed by [`Drop`]/the trampoline. If it is
+    /// dropped while *suspended partway through* the closure (i.e. after at least one
+    /// resume, but before the closure returns), the closure's captures are stuck on
+    /// that now-destroyed stack frame and are leaked rather than dropped: minicoro has
+    /// no mechanism to unwind a suspended stack, so there is no safe way to run their
+    /// destructors.
+    ///
+    /// # Errors
+    ///
+    /// Returns `CoroutineError` if coroutine creation fails
+    pub fn spawn<F>(f: F, stack_size: usize) -> Result<Self, CoroutineError>
+    where
+        F: FnOnce() + 'static,
+    {
+        let entry: Box<Entry> = Box::new(Entry {
+            body: Some(Box::new(f)),
+            #[cfg(feature = "std")]
+            panic: None,
+        });
+        let user_data = Box::into_raw(entry).cast::<core::ffi::c_void>();
+
+        let mut desc = unsafe { ffi::mco_desc_init(Some(trampoline), stack_size) };
+        desc.user_data = user_data;
+
+        let mut co: *mut ffi::mco_coro = ptr::null_mut();
+        let result = unsafe { ffi::mco_create(&raw mut co, &raw mut desc) };
+        if result == ffi::mco_result_MCO_SUCCESS {
+            Ok(Coroutine {
+                inner: co,
+                pool: None,
+                #[cfg(feature = "std")]
+                panic_reaped: false,
+            })
+        } else {
+            // The trampoline never ran, so nothing else will reclaim the closure.
+            unsafe { drop(Box::from_raw(user_data.cast::<Entry>())) };
+            Err(CoroutineError::from_raw(result))
+        }
+    }
+
     /// Resume the coroutine
     ///
+    /// With the `std` feature, if the body panicked on this resume, the panic is
+    /// caught at the `extern "C"` boundary rather than unwinding through it, and
+    /// reported back as `CoroutineError::Panicked`.
+    ///
     /// # Errors
     ///
-    /// Returns `CoroutineError` if resuming the coroutine fails
+    /// Returns `CoroutineError` if resuming the coroutine fails, or (with the `std`
+    /// feature) `CoroutineError::Panicked` if the body panicked on this resume
     pub fn resume(&mut self) -> Result<(), CoroutineError> {
         let result = unsafe { ffi::mco_resume(self.inner) };
+
+        #[cfg(feature = "std")]
+        if !self.panic_reaped && self.status() == CoroutineState::Dead {
+            self.panic_reaped = true;
+            let user_data = unsafe { ffi::mco_get_user_data(self.inner) };
+            if !user_data.is_null() {
+                let entry = unsafe { Box::from_raw(user_data.cast::<Entry>()) };
+                if entry.panic.is_some() {
+                    return Err(CoroutineError::Panicked);
+                }
+            }
+        }
+
         if result == ffi::mco_result_MCO_SUCCESS {
             Ok(())
         } else {
@@ -96,18 +222,7 @@ impl Coroutine {
     ///
     /// Returns `CoroutineError` if pushing data fails
     pub fn push<T>(&mut self, data: &T) -> Result<(), CoroutineError> {
-        let result = unsafe {
-            ffi::mco_push(
-                self.inner,
-                core::ptr::from_ref::<T>(data).cast::<core::ffi::c_void>(),
-                core::mem::size_of::<T>(),
-            )
-        };
-        if result == ffi::mco_result_MCO_SUCCESS {
-            Ok(())
-        } else {
-            Err(CoroutineError::from_raw(result))
-        }
+        push_raw(self.inner, data)
     }
 
     /// Pop data from the coroutine storage
@@ -116,19 +231,7 @@ impl Coroutine {
     ///
     /// Returns `CoroutineError` if popping data fails
     pub fn pop<T>(&mut self) -> Result<T, CoroutineError> {
-        let mut data = core::mem::MaybeUninit::<T>::uninit();
-        let result = unsafe {
-            ffi::mco_pop(
-                self.inner,
-                data.as_mut_ptr().cast::<core::ffi::c_void>(),
-                core::mem::size_of::<T>(),
-            )
-        };
-        if result == ffi::mco_result_MCO_SUCCESS {
-            Ok(unsafe { data.assume_init() })
-        } else {
-            Err(CoroutineError::from_raw(result))
-        }
+        pop_raw(self.inner)
     }
 
     /// Get the number of bytes stored in the coroutine storage
@@ -147,6 +250,16 @@ impl Coroutine {
 impl Drop for Coroutine {
     fn drop(&mut self) {
         if !self.inner.is_null() {
+            // If the coroutine never reached `Dead`, its `Entry` (if any) hasn't been
+            // reclaimed yet: `Dead` coroutines already had theirs freed, either by the
+            // trampoline itself (no `std`) or by `resume` (with `std`). Coroutines
+            // created via `new` have no `Entry`, hence the null check.
+            if self.status() != CoroutineState::Dead {
+                let user_data = unsafe { ffi::mco_get_user_data(self.inner) };
+                if !user_data.is_null() {
+                    unsafe { drop(Box::from_raw(user_data.cast::<Entry>())) };
+                }
+            }
             unsafe {
                 ffi::mco_destroy(self.inner);
             }
@@ -154,8 +267,84 @@ impl Drop for Coroutine {
     }
 }
 
+// SAFETY: `inner` is only ever resumed/yielded/destroyed by whichever thread
+// currently owns the `Coroutine`, never concurrently from two threads at once. The
+// `pool` field is sound to send because `StackPool` synchronizes all of its internal
+// raw-pointer access behind a lock (see its `unsafe impl Send`/`Sync`).
 unsafe impl Send for Coroutine {}
 
+/// Entry point installed for coroutines created via [`Coroutine::spawn`] or
+/// [`Coroutine::spawn_in`].
+///
+/// Reconstructs the boxed closure from `co`'s [`Entry`] and runs it.
+///
+/// Without the `std` feature, the `Entry` is dropped (freeing the closure) on the
+/// coroutine's own stack exactly once the closure returns. With `std`, a panicking
+/// closure is instead caught with `catch_unwind`, and the `Entry` is left in place for
+/// [`Coroutine::resume`] to reclaim once it observes the coroutine as `Dead` -
+/// otherwise the panic would unwind through this `extern "C"` frame, which is
+/// undefined behavior.
+unsafe extern "C" fn trampoline(co: *mut ffi::mco_coro) {
+    let ptr = unsafe { ffi::mco_get_user_data(co) }.cast::<Entry>();
+    let entry = unsafe { &mut *ptr };
+    let body = entry
+        .body
+        .take()
+        .expect("tinycoro: coroutine entry point invoked twice");
+
+    #[cfg(feature = "std")]
+    {
+        if let Err(payload) = std::panic::catch_unwind(std::panic::AssertUnwindSafe(body)) {
+            entry.panic = Some(payload);
+        }
+        // Left for `Coroutine::resume` to reclaim; see its doc comment.
+    }
+
+    #[cfg(not(feature = "std"))]
+    {
+        body();
+        unsafe { drop(Box::from_raw(ptr)) };
+    }
+}
+
+/// Push data onto a raw coroutine's storage, given its pointer directly.
+///
+/// Shared by [`Coroutine::push`] and code (such as [`generator`]) that only has
+/// access to the currently running `mco_coro*`, not a `&mut Coroutine`.
+pub(crate) fn push_raw<T>(co: *mut ffi::mco_coro, data: &T) -> Result<(), CoroutineError> {
+    let result = unsafe {
+        ffi::mco_push(
+            co,
+            core::ptr::from_ref::<T>(data).cast::<core::ffi::c_void>(),
+            core::mem::size_of::<T>(),
+        )
+    };
+    if result == ffi::mco_result_MCO_SUCCESS {
+        Ok(())
+    } else {
+        Err(CoroutineError::from_raw(result))
+    }
+}
+
+/// Pop data from a raw coroutine's storage, given its pointer directly.
+///
+/// See [`push_raw`] for why this exists alongside [`Coroutine::pop`].
+pub(crate) fn pop_raw<T>(co: *mut ffi::mco_coro) -> Result<T, CoroutineError> {
+    let mut data = core::mem::MaybeUninit::<T>::uninit();
+    let result = unsafe {
+        ffi::mco_pop(
+            co,
+            data.as_mut_ptr().cast::<core::ffi::c_void>(),
+            core::mem::size_of::<T>(),
+        )
+    };
+    if result == ffi::mco_result_MCO_SUCCESS {
+        Ok(unsafe { data.assume_init() })
+    } else {
+        Err(CoroutineError::from_raw(result))
+    }
+}
+
 /// Safe wrapper for coroutine states
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CoroutineState {
@@ -207,6 +396,11 @@ pub enum CoroutineError {
     StackOverflow,
     #[error("Unknown error")]
     Unknown,
+    /// The coroutine body panicked; the panic was caught at the `extern "C"`
+    /// boundary instead of unwinding through it.
+    #[cfg(feature = "std")]
+    #[error("Coroutine panicked")]
+    Panicked,
 }
 
 impl CoroutineError {
@@ -279,3 +473,64 @@ pub unsafe fn yield_current_unsafe() -> Result<(), CoroutineError> {
         Err(CoroutineError::InvalidCoroutine)
     }
 }
+
+// Requires the `std` feature for `catch_unwind`-backed panic reporting; see the
+// crate-level docs for why there's no equivalent test under plain `no_std`.
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use alloc::rc::Rc;
+    use core::cell::Cell;
+
+    const STACK_SIZE: usize = 64 * 1024;
+
+    #[test]
+    fn spawn_runs_closure_and_reaches_dead() {
+        let ran = Rc::new(Cell::new(false));
+        let ran_in_body = Rc::clone(&ran);
+        let mut co = Coroutine::spawn(move || ran_in_body.set(true), STACK_SIZE).unwrap();
+
+        assert_eq!(co.status(), CoroutineState::Suspended);
+        co.resume().unwrap();
+        assert_eq!(co.status(), CoroutineState::Dead);
+        assert!(ran.get());
+    }
+
+    #[test]
+    fn resuming_past_completion_errors_instead_of_running_again() {
+        let ran_count = Rc::new(Cell::new(0));
+        let ran_count_in_body = Rc::clone(&ran_count);
+        let mut co = Coroutine::spawn(
+            move || ran_count_in_body.set(ran_count_in_body.get() + 1),
+            STACK_SIZE,
+        )
+        .unwrap();
+
+        co.resume().unwrap();
+        assert_eq!(ran_count.get(), 1);
+
+        let err = co.resume().unwrap_err();
+        assert_eq!(err, CoroutineError::NotSuspended);
+        assert_eq!(ran_count.get(), 1);
+    }
+
+    #[test]
+    fn panicking_body_is_caught_and_reported() {
+        let mut co = Coroutine::spawn(|| panic!("boom"), STACK_SIZE).unwrap();
+        let err = co.resume().unwrap_err();
+        assert_eq!(err, CoroutineError::Panicked);
+        assert_eq!(co.status(), CoroutineState::Dead);
+    }
+
+    #[test]
+    fn dropping_before_first_resume_reclaims_the_closure() {
+        let ran = Rc::new(Cell::new(false));
+        let ran_in_body = Rc::clone(&ran);
+        let co = Coroutine::spawn(move || ran_in_body.set(true), STACK_SIZE).unwrap();
+
+        drop(co);
+
+        assert!(!ran.get());
+        assert_eq!(Rc::strong_count(&ran), 1);
+    }
+}
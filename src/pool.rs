@@ -0,0 +1,256 @@
+//! A stack-recycling pool, amortizing coroutine creation cost for workloads that
+//! spawn and destroy many short-lived coroutines.
+
+use crate::ffi;
+use crate::{Coroutine, CoroutineError, Entry};
+use alloc::alloc::{alloc, dealloc, handle_alloc_error};
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::alloc::Layout;
+use core::cell::UnsafeCell;
+use core::ffi::c_void;
+use core::ops::{Deref, DerefMut};
+use core::ptr;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// A minimal spinlock used in place of `RefCell` so a [`StackPool`] can safely be
+/// shared (via `Arc`) with coroutines that get sent to, and dropped or spawned from,
+/// another thread. `RefCell`'s borrow flag isn't synchronized at all, so two threads
+/// touching it concurrently is undefined behavior even without an observable overlap.
+struct SpinLock<T> {
+    locked: AtomicBool,
+    value: UnsafeCell<T>,
+}
+
+impl<T> SpinLock<T> {
+    const fn new(value: T) -> Self {
+        SpinLock {
+            locked: AtomicBool::new(false),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    fn lock(&self) -> SpinLockGuard<'_, T> {
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+        SpinLockGuard { lock: self }
+    }
+}
+
+struct SpinLockGuard<'a, T> {
+    lock: &'a SpinLock<T>,
+}
+
+impl<T> Deref for SpinLockGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> DerefMut for SpinLockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for SpinLockGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.locked.store(false, Ordering::Release);
+    }
+}
+
+/// Caches freed coroutine buffers (stack + control structure) keyed by their size, so
+/// that repeatedly spawning and destroying short-lived coroutines doesn't repeatedly
+/// hit the global allocator.
+///
+/// Wired into minicoro through `mco_desc`'s `alloc_cb`/`dealloc_cb`/`allocator_data`:
+/// minicoro asks the pool for a buffer of a given size on creation, and returns it to
+/// the pool instead of freeing it on `mco_destroy`.
+///
+/// Share a pool between coroutines through `Arc<StackPool>` (see
+/// [`Coroutine::spawn_in`]): each pool-backed `Coroutine` holds a clone of the `Arc`,
+/// so the pool can't be dropped while a coroutine still references it through
+/// `allocator_data`.
+pub struct StackPool {
+    free_lists: SpinLock<BTreeMap<usize, Vec<ptr::NonNull<u8>>>>,
+    capacity_per_size: usize,
+}
+
+// SAFETY: the only fields holding raw pointers (`free_lists`'s `NonNull<u8>`s) are
+// always accessed through `SpinLock`, which provides the synchronization that
+// `NonNull` doesn't get for free.
+unsafe impl Send for StackPool {}
+unsafe impl Sync for StackPool {}
+
+impl StackPool {
+    /// Create an empty pool that caches at most `capacity_per_size` freed buffers for
+    /// each distinct size requested, so pooled memory cannot grow unbounded.
+    #[must_use]
+    pub fn new(capacity_per_size: usize) -> Self {
+        StackPool {
+            free_lists: SpinLock::new(BTreeMap::new()),
+            capacity_per_size,
+        }
+    }
+
+    fn take(&self, size: usize) -> *mut c_void {
+        if let Some(buf) = self.free_lists.lock().get_mut(&size).and_then(Vec::pop) {
+            return buf.as_ptr().cast::<c_void>();
+        }
+
+        let layout = Self::layout_for(size);
+        let ptr = unsafe { alloc(layout) };
+        if ptr.is_null() {
+            handle_alloc_error(layout);
+        }
+        ptr.cast::<c_void>()
+    }
+
+    fn give_back(&self, ptr: *mut c_void, size: usize) {
+        let Some(ptr) = ptr::NonNull::new(ptr.cast::<u8>()) else {
+            return;
+        };
+
+        let mut free_lists = self.free_lists.lock();
+        let list = free_lists.entry(size).or_default();
+        if list.len() < self.capacity_per_size {
+            list.push(ptr);
+        } else {
+            drop(free_lists);
+            unsafe { dealloc(ptr.as_ptr(), Self::layout_for(size)) };
+        }
+    }
+
+    /// The alignment minicoro's own (`malloc`-based) default allocator effectively
+    /// guarantees for a coroutine's stack + control block. Using anything less (e.g.
+    /// `align_of::<usize>()`) would be a regression versus the unpooled path.
+    fn layout_for(size: usize) -> Layout {
+        Layout::from_size_align(size, core::mem::align_of::<u128>())
+            .expect("coroutine allocation size overflows an isize")
+    }
+}
+
+impl Drop for StackPool {
+    fn drop(&mut self) {
+        for (size, list) in self.free_lists.lock().iter() {
+            let layout = Self::layout_for(*size);
+            for ptr in list {
+                unsafe { dealloc(ptr.as_ptr(), layout) };
+            }
+        }
+    }
+}
+
+unsafe extern "C" fn pool_alloc(size: usize, allocator_data: *mut c_void) -> *mut c_void {
+    let pool = unsafe { &*allocator_data.cast::<StackPool>() };
+    pool.take(size)
+}
+
+unsafe extern "C" fn pool_dealloc(ptr: *mut c_void, size: usize, allocator_data: *mut c_void) {
+    let pool = unsafe { &*allocator_data.cast::<StackPool>() };
+    pool.give_back(ptr, size);
+}
+
+impl Coroutine {
+    /// Spawn a coroutine from a closure, allocating its stack from `pool` instead of
+    /// the global allocator.
+    ///
+    /// The returned `Coroutine` holds a clone of `pool`, so the pool is kept alive for
+    /// at least as long as any coroutine spawned from it, even if the caller drops its
+    /// own `Arc`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `CoroutineError` if coroutine creation fails.
+    pub fn spawn_in<F>(
+        pool: &Arc<StackPool>,
+        f: F,
+        stack_size: usize,
+    ) -> Result<Self, CoroutineError>
+    where
+        F: FnOnce() + 'static,
+    {
+        let entry: Box<Entry> = Box::new(Entry {
+            body: Some(Box::new(f)),
+            #[cfg(feature = "std")]
+            panic: None,
+        });
+        let user_data = Box::into_raw(entry).cast::<c_void>();
+
+        let mut desc = unsafe { ffi::mco_desc_init(Some(crate::trampoline), stack_size) };
+        desc.user_data = user_data;
+        desc.alloc_cb = Some(pool_alloc);
+        desc.dealloc_cb = Some(pool_dealloc);
+        desc.allocator_data = Arc::as_ptr(pool).cast_mut().cast::<c_void>();
+
+        let mut co: *mut ffi::mco_coro = ptr::null_mut();
+        let result = unsafe { ffi::mco_create(&raw mut co, &raw mut desc) };
+        if result == ffi::mco_result_MCO_SUCCESS {
+            Ok(Coroutine {
+                inner: co,
+                pool: Some(Arc::clone(pool)),
+                #[cfg(feature = "std")]
+                panic_reaped: false,
+            })
+        } else {
+            unsafe { drop(Box::from_raw(user_data.cast::<Entry>())) };
+            Err(CoroutineError::from_raw(result))
+        }
+    }
+}
+
+// Requires the `std` feature; see the module-level comment on the `lib.rs` test module
+// for why there's no equivalent under plain `no_std`.
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::CoroutineState;
+
+    const STACK_SIZE: usize = 64 * 1024;
+
+    #[test]
+    fn spawn_in_reuses_freed_buffers_across_coroutines() {
+        let pool = Arc::new(StackPool::new(4));
+        for _ in 0..8 {
+            let mut co = Coroutine::spawn_in(&pool, || {}, STACK_SIZE).unwrap();
+            co.resume().unwrap();
+            assert_eq!(co.status(), CoroutineState::Dead);
+        }
+        // Each coroutine reused the single buffer its predecessor gave back, so only
+        // one is cached (without reuse, `capacity_per_size` = 4 would be).
+        let cached: usize = pool.free_lists.lock().values().map(Vec::len).sum();
+        assert_eq!(cached, 1);
+    }
+
+    #[test]
+    fn coroutine_outlives_the_callers_pool_handle() {
+        let pool = Arc::new(StackPool::new(4));
+        let mut co = Coroutine::spawn_in(&pool, || {}, STACK_SIZE).unwrap();
+        // `co` holds its own clone of the `Arc`, so the pool stays alive (and usable
+        // by the deallocation callback that `mco_destroy` invokes) even though the
+        // caller's handle is gone.
+        drop(pool);
+
+        co.resume().unwrap();
+        assert_eq!(co.status(), CoroutineState::Dead);
+    }
+
+    #[test]
+    fn resuming_past_completion_does_not_corrupt_the_pool() {
+        let pool = Arc::new(StackPool::new(4));
+        let mut co = Coroutine::spawn_in(&pool, || {}, STACK_SIZE).unwrap();
+        co.resume().unwrap();
+
+        assert!(co.resume().is_err());
+        assert_eq!(co.status(), CoroutineState::Dead);
+    }
+}
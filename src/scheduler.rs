@@ -0,0 +1,161 @@
+//! A cooperative scheduler that drives many spawned coroutines to completion.
+
+use crate::{yield_current, Coroutine, CoroutineError, CoroutineState};
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+/// Opaque handle to a task enqueued on a [`Scheduler`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TaskId(usize);
+
+struct Task {
+    id: TaskId,
+    coroutine: Coroutine,
+}
+
+/// A single-threaded, FIFO cooperative scheduler.
+///
+/// `add`/`spawn` enqueue `Suspended` coroutines; `run` repeatedly pops the next
+/// runnable task, resumes it once, and re-enqueues it if it's still `Suspended`,
+/// dropping it once it reaches `Dead`. `run` returns once the ready queue is empty.
+#[derive(Default)]
+pub struct Scheduler {
+    ready: VecDeque<Task>,
+    next_id: usize,
+}
+
+impl Scheduler {
+    /// Create an empty scheduler.
+    #[must_use]
+    pub fn new() -> Self {
+        Scheduler {
+            ready: VecDeque::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Enqueue an already-created, suspended coroutine.
+    pub fn add(&mut self, coroutine: Coroutine) -> TaskId {
+        let id = TaskId(self.next_id);
+        self.next_id += 1;
+        self.ready.push_back(Task { id, coroutine });
+        id
+    }
+
+    /// Spawn a closure as a new task and enqueue it.
+    ///
+    /// # Errors
+    ///
+    /// Returns `CoroutineError` if the underlying coroutine cannot be created.
+    pub fn spawn<F>(&mut self, f: F, stack_size: usize) -> Result<TaskId, CoroutineError>
+    where
+        F: FnOnce() + 'static,
+    {
+        let coroutine = Coroutine::spawn(f, stack_size)?;
+        Ok(self.add(coroutine))
+    }
+
+    /// The status of a task, if it's still queued.
+    ///
+    /// Returns `None` once the task has run to completion and been dropped.
+    #[must_use]
+    pub fn status(&self, id: TaskId) -> Option<CoroutineState> {
+        self.ready
+            .iter()
+            .find(|task| task.id == id)
+            .map(|task| task.coroutine.status())
+    }
+
+    /// Run every queued task to completion, in FIFO round-robin order.
+    ///
+    /// A task that errors on resume is still re-enqueued if it's left `Suspended`
+    /// (rather than dropped, which would leak its captured state per `Coroutine`'s
+    /// own `Drop` semantics), and does not stop other tasks from running: `run` always
+    /// drains the whole ready queue, returning every per-task error it collected along
+    /// the way.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors produced by resuming tasks, each paired with the `TaskId` of
+    /// the task that produced it, in the order they occurred. Returns `Ok(())` when
+    /// every task ran to completion without error.
+    pub fn run(&mut self) -> Result<(), Vec<(TaskId, CoroutineError)>> {
+        let mut errors = Vec::new();
+        while let Some(mut task) = self.ready.pop_front() {
+            if let Err(err) = task.coroutine.resume() {
+                errors.push((task.id, err));
+            }
+            if task.coroutine.status() == CoroutineState::Suspended {
+                self.ready.push_back(task);
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Yield the current task back to the [`Scheduler`] driving it.
+///
+/// # Errors
+///
+/// Returns `CoroutineError` if called outside of a running coroutine.
+pub fn yield_to_scheduler() -> Result<(), CoroutineError> {
+    yield_current()
+}
+
+// Requires the `std` feature; see the module-level comment on the `lib.rs` test module
+// for why there's no equivalent under plain `no_std`.
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use alloc::rc::Rc;
+    use core::cell::Cell;
+
+    const STACK_SIZE: usize = 64 * 1024;
+
+    #[test]
+    fn run_drains_tasks_that_yield_before_completing() {
+        let mut scheduler = Scheduler::new();
+        let runs = Rc::new(Cell::new(0));
+
+        for _ in 0..3 {
+            let runs = Rc::clone(&runs);
+            scheduler
+                .spawn(
+                    move || {
+                        yield_to_scheduler().unwrap();
+                        runs.set(runs.get() + 1);
+                    },
+                    STACK_SIZE,
+                )
+                .unwrap();
+        }
+
+        assert_eq!(scheduler.run(), Ok(()));
+        assert_eq!(runs.get(), 3);
+    }
+
+    #[test]
+    fn run_keeps_draining_the_queue_after_a_task_panics() {
+        let mut scheduler = Scheduler::new();
+        scheduler.spawn(|| panic!("boom"), STACK_SIZE).unwrap();
+
+        let ran = Rc::new(Cell::new(false));
+        let ran_in_body = Rc::clone(&ran);
+        let ok_task = scheduler
+            .spawn(move || ran_in_body.set(true), STACK_SIZE)
+            .unwrap();
+
+        let errors = scheduler.run().unwrap_err();
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].1, CoroutineError::Panicked);
+        assert!(ran.get());
+        // The panicking task reached `Dead` (not left dangling `Suspended`), and the
+        // healthy task ran to completion and was dropped by `run`.
+        assert_eq!(scheduler.status(ok_task), None);
+    }
+}